@@ -0,0 +1,205 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const XDG_TERMINAL_EXEC: &str = "xdg-terminal-exec";
+const CACHE_FILE: &str = "cache";
+
+/// Set (to any value) to skip the cache entirely, forcing a full rescan.
+pub const NO_CACHE_ENV: &str = "XDG_TERMINAL_EXEC_NO_CACHE";
+
+fn cache_path() -> io::Result<PathBuf> {
+    let dirs = xdg::BaseDirectories::with_prefix(XDG_TERMINAL_EXEC)?;
+    dirs.place_cache_file(CACHE_FILE)
+}
+
+fn mtime_secs(path: &Path) -> io::Result<u64> {
+    let mtime = fs::metadata(path)?.modified()?;
+    Ok(mtime
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs())
+}
+
+pub fn bypassed() -> bool {
+    std::env::var_os(NO_CACHE_ENV).is_some()
+}
+
+/// Removes the cache file, forcing the next lookup to rescan.
+pub fn clear() -> io::Result<()> {
+    clear_at(&cache_path()?)
+}
+
+fn clear_at(cache_path: &Path) -> io::Result<()> {
+    match fs::remove_file(cache_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Looks up the entry path cached for `desktop_key`, if the cache is still
+/// fresh: the entry file must still exist with the mtime it had when cached,
+/// and none of `config_paths` may be newer than the cache itself.
+pub fn lookup(desktop_key: &str, config_paths: &[PathBuf]) -> io::Result<Option<PathBuf>> {
+    lookup_at(&cache_path()?, desktop_key, config_paths)
+}
+
+fn lookup_at(
+    cache_path: &Path,
+    desktop_key: &str,
+    config_paths: &[PathBuf],
+) -> io::Result<Option<PathBuf>> {
+    let Ok(cache_mtime) = mtime_secs(cache_path) else {
+        return Ok(None);
+    };
+    let stale = config_paths
+        .iter()
+        .any(|path| mtime_secs(path).is_ok_and(|mtime| mtime > cache_mtime));
+    if stale {
+        return Ok(None);
+    }
+    let Ok(contents) = fs::read_to_string(cache_path) else {
+        return Ok(None);
+    };
+    for line in contents.lines() {
+        let Some((key, rest)) = line.split_once('\t') else {
+            continue;
+        };
+        if key != desktop_key {
+            continue;
+        }
+        let Some((path, mtime)) = rest.rsplit_once('\t') else {
+            continue;
+        };
+        let Ok(mtime) = mtime.parse::<u64>() else {
+            continue;
+        };
+        let path = PathBuf::from(path);
+        if mtime_secs(&path).ok() == Some(mtime) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(None)
+}
+
+/// Records `entry_path` as the resolved entry for `desktop_key`, replacing
+/// any previous entry for that key.
+pub fn store(desktop_key: &str, entry_path: &Path) -> io::Result<()> {
+    store_at(&cache_path()?, desktop_key, entry_path)
+}
+
+fn store_at(cache_path: &Path, desktop_key: &str, entry_path: &Path) -> io::Result<()> {
+    let mtime = mtime_secs(entry_path)?;
+    let prefix = format!("{}\t", desktop_key);
+    let mut lines: Vec<String> = fs::read_to_string(cache_path)
+        .map(|contents| {
+            contents
+                .lines()
+                .filter(|line| !line.starts_with(&prefix))
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
+    lines.push(format!("{}{}\t{}", prefix, entry_path.display(), mtime));
+    fs::write(cache_path, lines.join("\n") + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn test_dir() -> PathBuf {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!(
+            "xdg-terminal-exec-cache-test-{}-{}",
+            std::process::id(),
+            id
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn touch(path: &Path) {
+        fs::write(path, b"").unwrap();
+    }
+
+    #[test]
+    fn store_then_lookup_round_trips() {
+        let dir = test_dir();
+        let cache_path = dir.join("cache");
+        let entry_path = dir.join("kitty.desktop");
+        touch(&entry_path);
+
+        store_at(&cache_path, "KDE", &entry_path).unwrap();
+        let found = lookup_at(&cache_path, "KDE", &[]).unwrap();
+
+        assert_eq!(found, Some(entry_path));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn lookup_misses_when_a_config_list_is_newer_than_the_cache() {
+        let dir = test_dir();
+        let cache_path = dir.join("cache");
+        let entry_path = dir.join("kitty.desktop");
+        touch(&entry_path);
+        store_at(&cache_path, "KDE", &entry_path).unwrap();
+
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let config_path = dir.join("xdg-terminals.list");
+        touch(&config_path);
+
+        let found = lookup_at(&cache_path, "KDE", &[config_path]).unwrap();
+
+        assert_eq!(found, None);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn lookup_misses_when_the_cached_entry_file_is_gone() {
+        let dir = test_dir();
+        let cache_path = dir.join("cache");
+        let entry_path = dir.join("kitty.desktop");
+        touch(&entry_path);
+        store_at(&cache_path, "KDE", &entry_path).unwrap();
+
+        fs::remove_file(&entry_path).unwrap();
+        let found = lookup_at(&cache_path, "KDE", &[]).unwrap();
+
+        assert_eq!(found, None);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn store_only_replaces_the_matching_desktop_key() {
+        let dir = test_dir();
+        let cache_path = dir.join("cache");
+        let kde_entry = dir.join("kitty.desktop");
+        let gnome_entry = dir.join("gnome-terminal.desktop");
+        let kde_entry_2 = dir.join("konsole.desktop");
+        touch(&kde_entry);
+        touch(&gnome_entry);
+        touch(&kde_entry_2);
+
+        store_at(&cache_path, "KDE", &kde_entry).unwrap();
+        store_at(&cache_path, "GNOME", &gnome_entry).unwrap();
+        store_at(&cache_path, "KDE", &kde_entry_2).unwrap();
+
+        let contents = fs::read_to_string(&cache_path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert_eq!(
+            lookup_at(&cache_path, "KDE", &[]).unwrap(),
+            Some(kde_entry_2)
+        );
+        assert_eq!(
+            lookup_at(&cache_path, "GNOME", &[]).unwrap(),
+            Some(gnome_entry)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+}
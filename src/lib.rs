@@ -0,0 +1,583 @@
+use std::{
+    collections::HashSet,
+    env, fs, io,
+    iter::once,
+    os::unix::process::CommandExt,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use freedesktop_entry_parser::Entry;
+
+mod cache;
+
+pub use cache::NO_CACHE_ENV;
+
+const XDG_TERMINALS: &str = "xdg-terminals";
+const XDG_TERMINALS_LIST: &str = "xdg-terminals.list";
+
+/// A terminal entry considered during resolution, in priority order.
+pub struct Candidate {
+    pub path: PathBuf,
+}
+
+/// The terminal entry selected by [`resolve`], with its `Exec` already
+/// tokenized and its field codes already expanded.
+pub struct ResolvedTerminal {
+    pub path: PathBuf,
+    pub exec: Vec<String>,
+    pub exec_arg: String,
+}
+
+fn desktops() -> Result<Vec<String>, env::VarError> {
+    let xdg_current_desktop = env::var("XDG_CURRENT_DESKTOP")?;
+    let ids = xdg_current_desktop
+        // .to_ascii_lowercase()
+        .split(':')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_owned())
+        .collect();
+    Ok(ids)
+}
+
+fn config_file_names(desktops: &[String]) -> Vec<String> {
+    desktops
+        .iter()
+        .map(|desktop| format!("{}-{}", desktop, XDG_TERMINALS_LIST))
+        .chain(once(XDG_TERMINALS_LIST.to_owned()))
+        .collect()
+}
+
+fn config_paths(config_file_names: &[String]) -> Result<Vec<PathBuf>, xdg::BaseDirectoriesError> {
+    let dirs = xdg::BaseDirectories::new()?;
+    let config_dirs = once(dirs.get_config_home()).chain(dirs.get_config_dirs());
+    let config_paths = config_dirs
+        .flat_map(|dir| config_file_names.iter().map(move |path| dir.join(path)))
+        .filter(|path| path.try_exists().unwrap_or(false));
+    Ok(config_paths.collect())
+}
+
+/// Parses the entries listed in the text of a `xdg-terminals(-<desktop>).list`
+/// file: one desktop-file ID or absolute path per line, blank lines and lines
+/// starting with `#` ignored, and surrounding whitespace trimmed.
+fn parse_config_list(text: &str) -> Vec<PathBuf> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn configured_entries(desktops: &[String]) -> io::Result<Vec<PathBuf>> {
+    let config_file_names = config_file_names(desktops);
+    let config_paths = config_paths(&config_file_names)?;
+    let configs = config_paths
+        .iter()
+        .map(fs::read_to_string)
+        .collect::<io::Result<Vec<_>>>()?;
+    let paths = configs
+        .iter()
+        .flat_map(|text| parse_config_list(text))
+        .collect();
+    Ok(paths)
+}
+
+fn present_entries(dirs: &xdg::BaseDirectories) -> io::Result<Vec<PathBuf>> {
+    let dirs = once(dirs.get_data_home())
+        .chain(dirs.get_data_dirs())
+        .filter(|path| path.try_exists().unwrap_or(false));
+    let dirs = dirs.map(fs::read_dir).collect::<io::Result<Vec<_>>>()?;
+    let dirs = dirs.into_iter().flatten().collect::<io::Result<Vec<_>>>()?;
+    let paths = dirs
+        .iter()
+        .map(|dir| PathBuf::from(dir.file_name()))
+        .collect();
+    Ok(paths)
+}
+
+/// Why a candidate entry was accepted or rejected, as reported by
+/// [`evaluate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// Could not be parsed as a desktop entry file.
+    Unreadable,
+    /// `Hidden=true`.
+    Hidden,
+    /// Filtered out by `OnlyShowIn`/`NotShowIn` for the current
+    /// `XDG_CURRENT_DESKTOP`.
+    NotShownInDesktop,
+    /// `TryExec` names a binary that was not found on `$PATH`.
+    MissingTryExec,
+    /// Passed every filter, but a higher-priority candidate already won.
+    Superseded,
+}
+
+impl Reason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Reason::Unreadable => "could not be parsed as a desktop entry",
+            Reason::Hidden => "hidden (Hidden=true)",
+            Reason::NotShownInDesktop => "not shown on this desktop (OnlyShowIn/NotShowIn)",
+            Reason::MissingTryExec => "TryExec binary not found on PATH",
+            Reason::Superseded => "accepted, but a higher-priority terminal was already selected",
+        }
+    }
+}
+
+fn entry(path: &PathBuf, desktops: &[String]) -> Result<Entry, Reason> {
+    let entry = Entry::parse_file(path).map_err(|_| Reason::Unreadable)?;
+    let section = entry.section("Desktop Entry");
+    if section.attr("Hidden") == Some("true") {
+        return Err(Reason::Hidden);
+    }
+    if let Some(not_show_in) = section.attr("NotShowIn") {
+        if not_show_in
+            .split_terminator(';')
+            .any(|item| desktops.iter().any(|desktop| desktop == item))
+        {
+            return Err(Reason::NotShownInDesktop);
+        }
+    }
+    if let Some(only_show_in) = section.attr("OnlyShowIn") {
+        if !only_show_in
+            .split_terminator(';')
+            .any(|item| desktops.iter().any(|desktop| desktop == item))
+        {
+            return Err(Reason::NotShownInDesktop);
+        }
+    }
+    if let Some(try_exec) = section.attr("TryExec") {
+        if which::which(try_exec).is_err() {
+            return Err(Reason::MissingTryExec);
+        }
+    }
+    Ok(entry)
+}
+
+/// Splits an `Exec` value into tokens, per the Desktop Entry Specification:
+/// words are separated by unquoted whitespace, and a double-quoted token may
+/// contain `\\`, `` \` ``, `\$` and `\"` as escapes for the literal character.
+fn tokenize_exec(exec: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = exec.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '"' {
+            in_token = true;
+            while let Some(&next) = chars.peek() {
+                chars.next();
+                if next == '"' {
+                    break;
+                }
+                if next == '\\' {
+                    match chars.peek() {
+                        Some('\\' | '`' | '$' | '"') => current.push(chars.next().unwrap()),
+                        _ => current.push('\\'),
+                    }
+                } else {
+                    current.push(next);
+                }
+            }
+        } else if c.is_whitespace() {
+            if in_token {
+                tokens.push(std::mem::take(&mut current));
+                in_token = false;
+            }
+        } else {
+            in_token = true;
+            current.push(c);
+        }
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Expands the field codes in a single `Exec` token, per the Desktop Entry
+/// Specification. Returns `None` if the token should be dropped entirely:
+/// `%f`/`%F`/`%u`/`%U` (a terminal takes no file or URL arguments) and the
+/// deprecated `%d %D %n %N %v %m`.
+fn expand_token(token: &str, name: &str, icon: Option<&str>, path: &Path) -> Option<Vec<String>> {
+    if token == "%i" {
+        return icon.map(|icon| vec!["--icon".to_owned(), icon.to_owned()]);
+    }
+    let mut expanded = String::with_capacity(token.len());
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some('c') => expanded.push_str(name),
+            Some('k') => expanded.push_str(&path.to_string_lossy()),
+            Some('f' | 'F' | 'u' | 'U' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm') => return None,
+            Some(other) => {
+                expanded.push('%');
+                expanded.push(other);
+            }
+            None => expanded.push('%'),
+        }
+    }
+    Some(vec![expanded])
+}
+
+fn expand_field_codes(tokens: &[String], name: &str, icon: Option<&str>, path: &Path) -> Vec<String> {
+    tokens
+        .iter()
+        .filter_map(|token| expand_token(token, name, icon, path))
+        .flatten()
+        .collect()
+}
+
+/// Resolves a configured or present entry to the desktop-file path it
+/// names: an absolute path is used directly (if it exists), while a bare
+/// desktop-file ID is looked up in the `xdg-terminals` data directories.
+fn resolve_entry_path(dirs: &xdg::BaseDirectories, entry_path: PathBuf) -> Option<PathBuf> {
+    if entry_path.is_absolute() {
+        entry_path.try_exists().unwrap_or(false).then_some(entry_path)
+    } else {
+        dirs.find_data_file(entry_path)
+    }
+}
+
+/// Returns every candidate terminal entry, in the order they are evaluated:
+/// entries named in the `xdg-terminals(-<desktop>).list` config files first,
+/// then every entry present under `xdg-terminals/`.
+pub fn candidates() -> io::Result<Vec<Candidate>> {
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+    let desktops = desktops().unwrap_or_default();
+    let dirs = xdg::BaseDirectories::with_prefix(XDG_TERMINALS)?;
+    let entry_paths = configured_entries(&desktops)?
+        .into_iter()
+        .chain(present_entries(&dirs)?);
+    let candidates = entry_paths
+        .filter_map(|entry_path| resolve_entry_path(&dirs, entry_path))
+        .filter(|path| seen.insert(path.clone()))
+        .map(|path| Candidate { path })
+        .collect();
+    Ok(candidates)
+}
+
+/// The verdict [`evaluate`] reached for a single candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The first candidate to pass every filter; see [`evaluate`] for when
+    /// [`resolve`] picks a different one.
+    Selected,
+    /// Not picked, for the given [`Reason`].
+    Rejected(Reason),
+}
+
+/// A candidate together with the verdict reached for it, as returned by
+/// [`evaluate`].
+pub struct Evaluated {
+    pub candidate: Candidate,
+    pub verdict: Verdict,
+}
+
+/// Evaluates every candidate in priority order with the same filters as
+/// [`resolve`], but without exec'ing: reports why each one was accepted or
+/// rejected instead of stopping at the first match. Useful for diagnosing
+/// why a given terminal is or isn't chosen.
+///
+/// The cache is not read, and `Exec` is not checked: a candidate whose
+/// `Exec` expands to no tokens is reported as selected here, while
+/// [`resolve`] skips it. A fresh cache entry can also make [`resolve`]
+/// return a different terminal than the one selected here.
+pub fn evaluate() -> io::Result<Vec<Evaluated>> {
+    let desktops = desktops().unwrap_or_default();
+    Ok(evaluate_candidates(candidates()?, &desktops))
+}
+
+fn evaluate_candidates(candidates: Vec<Candidate>, desktops: &[String]) -> Vec<Evaluated> {
+    let mut selected = false;
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let verdict = match entry(&candidate.path, desktops) {
+                Ok(_) if selected => Verdict::Rejected(Reason::Superseded),
+                Ok(_) => {
+                    selected = true;
+                    Verdict::Selected
+                }
+                Err(reason) => Verdict::Rejected(reason),
+            };
+            Evaluated { candidate, verdict }
+        })
+        .collect()
+}
+
+fn resolved_terminal(entry: &Entry, path: PathBuf) -> Option<ResolvedTerminal> {
+    let section = entry.section("Desktop Entry");
+    let exec_str = section.attr("Exec").expect("attribute `Exec` is required");
+    let exec_arg = section
+        .attr("X-ExecArg")
+        .or_else(|| section.attr("ExecArg"))
+        .unwrap_or("-e");
+    let name = section.attr("Name").unwrap_or_default();
+    let icon = section.attr("Icon");
+    let exec = expand_field_codes(&tokenize_exec(exec_str), name, icon, &path);
+    if exec.is_empty() {
+        return None;
+    }
+    Some(ResolvedTerminal {
+        path,
+        exec,
+        exec_arg: exec_arg.to_owned(),
+    })
+}
+
+/// Clears the cache used by [`resolve`], forcing the next call to rescan.
+pub fn clear_cache() -> io::Result<()> {
+    cache::clear()
+}
+
+/// Selects the first candidate whose entry is not hidden, matches the
+/// current desktop, and whose `TryExec` (if any) resolves on `$PATH`.
+///
+/// The result is cached under `$XDG_CACHE_HOME/xdg-terminal-exec`, keyed by
+/// `XDG_CURRENT_DESKTOP`, to avoid rescanning every config list and data
+/// directory on every invocation. Set [`NO_CACHE_ENV`] to skip the cache.
+pub fn resolve() -> io::Result<Option<ResolvedTerminal>> {
+    let desktops = desktops().unwrap_or_default();
+    let desktop_key = env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    let use_cache = !cache::bypassed();
+    if use_cache {
+        let config_paths = config_paths(&config_file_names(&desktops)).unwrap_or_default();
+        if let Some(cached_path) = cache::lookup(&desktop_key, &config_paths).ok().flatten() {
+            if let Ok(entry) = entry(&cached_path, &desktops) {
+                if let Some(resolved) = resolved_terminal(&entry, cached_path) {
+                    return Ok(Some(resolved));
+                }
+            }
+        }
+    }
+    for candidate in candidates()? {
+        let Ok(entry) = entry(&candidate.path, &desktops) else {
+            continue;
+        };
+        let Some(resolved) = resolved_terminal(&entry, candidate.path) else {
+            continue;
+        };
+        if use_cache {
+            // Caching is a pure optimization; a write failure (read-only
+            // $HOME, unwritable $XDG_CACHE_HOME) must not block exec'ing.
+            let _ = cache::store(&desktop_key, &resolved.path);
+        }
+        return Ok(Some(resolved));
+    }
+    Ok(None)
+}
+
+/// Replaces the current process with the resolved terminal, appending
+/// `ExecArg` and `args` only when `args` is non-empty. Never returns on
+/// success; the returned [`io::Error`] is the `exec` failure.
+pub fn exec(resolved: &ResolvedTerminal, args: &[String]) -> io::Error {
+    let mut argv = resolved.exec.clone();
+    if !args.is_empty() {
+        argv.push(resolved.exec_arg.clone());
+        argv.extend_from_slice(args);
+    }
+    let mut cmd = Command::new(&argv[0]);
+    cmd.args(&argv[1..]);
+    cmd.exec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config_list_skips_blank_lines_and_comments() {
+        let text = "\n  \nfoo.desktop\n# a comment\n  # indented comment\n#no-space-comment\nbar.desktop\n";
+        assert_eq!(
+            parse_config_list(text),
+            vec![PathBuf::from("foo.desktop"), PathBuf::from("bar.desktop")]
+        );
+    }
+
+    #[test]
+    fn parse_config_list_trims_surrounding_whitespace() {
+        assert_eq!(
+            parse_config_list("  foo.desktop  \n\tbar.desktop\t\n"),
+            vec![PathBuf::from("foo.desktop"), PathBuf::from("bar.desktop")]
+        );
+    }
+
+    #[test]
+    fn parse_config_list_keeps_absolute_paths() {
+        assert_eq!(
+            parse_config_list("/etc/xdg/xdg-terminals/foo.desktop\n"),
+            vec![PathBuf::from("/etc/xdg/xdg-terminals/foo.desktop")]
+        );
+    }
+
+    #[test]
+    fn resolve_entry_path_accepts_an_existing_absolute_path() {
+        let dirs = xdg::BaseDirectories::with_prefix("xdg-terminal-exec-test").unwrap();
+        let path = std::env::temp_dir().join(format!(
+            "xdg-terminal-exec-resolve-entry-path-test-{}",
+            std::process::id()
+        ));
+        fs::write(&path, b"").unwrap();
+
+        assert_eq!(
+            resolve_entry_path(&dirs, path.clone()),
+            Some(path.clone())
+        );
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn resolve_entry_path_rejects_a_missing_absolute_path() {
+        let dirs = xdg::BaseDirectories::with_prefix("xdg-terminal-exec-test").unwrap();
+        let path = PathBuf::from("/nonexistent/xdg-terminal-exec-test/missing.desktop");
+
+        assert_eq!(resolve_entry_path(&dirs, path), None);
+    }
+
+    #[test]
+    fn resolve_entry_path_looks_up_a_bare_id_via_find_data_file() {
+        let dirs = xdg::BaseDirectories::with_prefix("xdg-terminal-exec-test").unwrap();
+        let bare_id = PathBuf::from("xdg-terminal-exec-test-entry-that-does-not-exist.desktop");
+
+        assert_eq!(resolve_entry_path(&dirs, bare_id), None);
+    }
+
+    #[test]
+    fn evaluate_candidates_marks_the_first_acceptable_entry_selected_and_rest_superseded() {
+        let dir = std::env::temp_dir().join(format!(
+            "xdg-terminal-exec-evaluate-test-{}",
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.desktop");
+        let second = dir.join("second.desktop");
+        fs::write(&first, "[Desktop Entry]\nType=Application\nName=First\nExec=first\n").unwrap();
+        fs::write(
+            &second,
+            "[Desktop Entry]\nType=Application\nName=Second\nExec=second\n",
+        )
+        .unwrap();
+        let candidates = vec![
+            Candidate { path: first },
+            Candidate { path: second },
+        ];
+
+        let evaluated = evaluate_candidates(candidates, &[]);
+
+        assert_eq!(evaluated.len(), 2);
+        assert_eq!(evaluated[0].verdict, Verdict::Selected);
+        assert_eq!(
+            evaluated[1].verdict,
+            Verdict::Rejected(Reason::Superseded)
+        );
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn tokenize_splits_unquoted_whitespace() {
+        assert_eq!(
+            tokenize_exec("foo -e bar"),
+            vec!["foo".to_owned(), "-e".to_owned(), "bar".to_owned()]
+        );
+        assert_eq!(
+            tokenize_exec("  foo   bar  "),
+            vec!["foo".to_owned(), "bar".to_owned()]
+        );
+    }
+
+    #[test]
+    fn tokenize_keeps_quoted_whitespace_together() {
+        assert_eq!(
+            tokenize_exec(r#"foo "terminal name" bar"#),
+            vec![
+                "foo".to_owned(),
+                "terminal name".to_owned(),
+                "bar".to_owned()
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_handles_quoted_escapes() {
+        assert_eq!(
+            tokenize_exec(r#""a\\b\"c\`d\$e""#),
+            vec![r#"a\b"c`d$e"#.to_owned()]
+        );
+    }
+
+    #[test]
+    fn tokenize_keeps_unrecognized_escape_literal() {
+        assert_eq!(tokenize_exec(r#""a\nb""#), vec![r"a\nb".to_owned()]);
+    }
+
+    #[test]
+    fn expand_token_substitutes_percent_percent() {
+        let path = Path::new("/entry.desktop");
+        assert_eq!(
+            expand_token("100%%", "name", None, path),
+            Some(vec!["100%".to_owned()])
+        );
+    }
+
+    #[test]
+    fn expand_token_substitutes_name_and_path() {
+        let path = Path::new("/usr/share/applications/foo.desktop");
+        assert_eq!(
+            expand_token("%c", "My Terminal", None, path),
+            Some(vec!["My Terminal".to_owned()])
+        );
+        assert_eq!(
+            expand_token("%k", "My Terminal", None, path),
+            Some(vec!["/usr/share/applications/foo.desktop".to_owned()])
+        );
+    }
+
+    #[test]
+    fn expand_token_icon_present() {
+        let path = Path::new("/entry.desktop");
+        assert_eq!(
+            expand_token("%i", "name", Some("foo-icon"), path),
+            Some(vec!["--icon".to_owned(), "foo-icon".to_owned()])
+        );
+    }
+
+    #[test]
+    fn expand_token_icon_absent_is_dropped() {
+        let path = Path::new("/entry.desktop");
+        assert_eq!(expand_token("%i", "name", None, path), None);
+    }
+
+    #[test]
+    fn expand_token_drops_file_and_url_codes() {
+        let path = Path::new("/entry.desktop");
+        for code in ["%f", "%F", "%u", "%U", "%d", "%D", "%n", "%N", "%v", "%m"] {
+            assert_eq!(expand_token(code, "name", None, path), None);
+        }
+    }
+
+    #[test]
+    fn expand_token_keeps_trailing_percent() {
+        let path = Path::new("/entry.desktop");
+        assert_eq!(
+            expand_token("100%", "name", None, path),
+            Some(vec!["100%".to_owned()])
+        );
+    }
+
+    #[test]
+    fn expand_field_codes_drops_tokens_and_keeps_order() {
+        let path = Path::new("/entry.desktop");
+        let tokens = tokenize_exec("foo %f -e %c");
+        assert_eq!(
+            expand_field_codes(&tokens, "My Terminal", None, path),
+            vec!["foo".to_owned(), "-e".to_owned(), "My Terminal".to_owned()]
+        );
+    }
+}